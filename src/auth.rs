@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+/// Verifies `Authorization` headers against a set of Argon2-hashed credentials loaded
+/// from `--auth-token-file`. Only constructed when that flag is set; its absence is what
+/// keeps the server fully open by default.
+pub struct AuthGate {
+    hashes: HashMap<String, String>,
+}
+
+pub enum AuthOutcome {
+    Authorized,
+    Unauthorized,
+    /// No `Authorization` header was present at all.
+    Missing,
+}
+
+impl AuthGate {
+    /// Loads a JSON file of `{username: argon2_hash}` entries.
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        let hashes: HashMap<String, String> = serde_json::from_str(&contents)?;
+        Ok(Self { hashes })
+    }
+
+    /// Checks a raw `Authorization` header value, supporting `Bearer <token>` (verified
+    /// against any stored hash) and `Basic <base64(username:password)>` (verified against
+    /// the hash stored for that username).
+    pub fn authorize(&self, header: Option<String>) -> AuthOutcome {
+        let header = match header {
+            Some(header) => header,
+            None => return AuthOutcome::Missing,
+        };
+
+        if let Some(token) = header.strip_prefix("Bearer ") {
+            return self.verify_any(token);
+        }
+
+        if let Some(encoded) = header.strip_prefix("Basic ") {
+            return match decode_basic(encoded) {
+                Some((username, password)) => self.verify_user(&username, &password),
+                None => AuthOutcome::Unauthorized,
+            };
+        }
+
+        AuthOutcome::Unauthorized
+    }
+
+    fn verify_any(&self, secret: &str) -> AuthOutcome {
+        let verified = self
+            .hashes
+            .values()
+            .any(|hash| argon2::verify_encoded(hash, secret.as_bytes()).unwrap_or(false));
+        if verified {
+            AuthOutcome::Authorized
+        } else {
+            AuthOutcome::Unauthorized
+        }
+    }
+
+    fn verify_user(&self, username: &str, secret: &str) -> AuthOutcome {
+        match self.hashes.get(username) {
+            Some(hash) if argon2::verify_encoded(hash, secret.as_bytes()).unwrap_or(false) => {
+                AuthOutcome::Authorized
+            }
+            _ => AuthOutcome::Unauthorized,
+        }
+    }
+}
+
+fn decode_basic(encoded: &str) -> Option<(String, String)> {
+    let decoded = base64::decode(encoded).ok()?;
+    let text = String::from_utf8(decoded).ok()?;
+    let (username, password) = text.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthGate, AuthOutcome};
+    use std::collections::HashMap;
+
+    fn hash(secret: &str) -> String {
+        argon2::hash_encoded(secret.as_bytes(), b"test-salt-1234", &argon2::Config::default())
+            .unwrap()
+    }
+
+    fn gate_with(username: &str, password: &str) -> AuthGate {
+        let mut hashes = HashMap::new();
+        hashes.insert(username.to_string(), hash(password));
+        AuthGate { hashes }
+    }
+
+    #[test]
+    fn authorize_should_be_missing_without_a_header() {
+        let gate = gate_with("alice", "secret");
+        assert!(matches!(gate.authorize(None), AuthOutcome::Missing));
+    }
+
+    #[test]
+    fn authorize_should_reject_an_unknown_scheme() {
+        let gate = gate_with("alice", "secret");
+        let result = gate.authorize(Some("Token abc".to_string()));
+        assert!(matches!(result, AuthOutcome::Unauthorized));
+    }
+
+    #[test]
+    fn authorize_should_accept_a_bearer_token_matching_any_stored_hash() {
+        let gate = gate_with("alice", "secret");
+        let result = gate.authorize(Some("Bearer secret".to_string()));
+        assert!(matches!(result, AuthOutcome::Authorized));
+    }
+
+    #[test]
+    fn authorize_should_reject_a_bearer_token_matching_no_stored_hash() {
+        let gate = gate_with("alice", "secret");
+        let result = gate.authorize(Some("Bearer wrong".to_string()));
+        assert!(matches!(result, AuthOutcome::Unauthorized));
+    }
+
+    #[test]
+    fn authorize_should_accept_basic_credentials_for_the_matching_username() {
+        let gate = gate_with("alice", "secret");
+        let header = format!("Basic {}", base64::encode("alice:secret"));
+        let result = gate.authorize(Some(header));
+        assert!(matches!(result, AuthOutcome::Authorized));
+    }
+
+    #[test]
+    fn authorize_should_reject_basic_credentials_with_the_wrong_password() {
+        let gate = gate_with("alice", "secret");
+        let header = format!("Basic {}", base64::encode("alice:wrong"));
+        let result = gate.authorize(Some(header));
+        assert!(matches!(result, AuthOutcome::Unauthorized));
+    }
+
+    #[test]
+    fn authorize_should_reject_basic_credentials_for_an_unknown_username() {
+        let gate = gate_with("alice", "secret");
+        let header = format!("Basic {}", base64::encode("bob:secret"));
+        let result = gate.authorize(Some(header));
+        assert!(matches!(result, AuthOutcome::Unauthorized));
+    }
+
+    #[test]
+    fn authorize_should_reject_malformed_basic_base64() {
+        let gate = gate_with("alice", "secret");
+        let result = gate.authorize(Some("Basic not-valid-base64!!".to_string()));
+        assert!(matches!(result, AuthOutcome::Unauthorized));
+    }
+}
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A single operation within a `/_batch` request.
+#[derive(Debug, Deserialize)]
+pub struct BatchOperation {
+    pub op: BatchOp,
+    pub path: String,
+    #[serde(default)]
+    pub body: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BatchOp {
+    Insert,
+    Get,
+}
+
+/// The per-operation result returned for a `/_batch` request.
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl BatchResult {
+    pub(crate) fn ok(status: u16, body: Value) -> Self {
+        Self { status, body }
+    }
+
+    pub(crate) fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: serde_json::json!({ "error": message }),
+        }
+    }
+}
@@ -4,4 +4,15 @@ pub use clap::Parser;
 pub struct CliParams {
     #[clap(short, long, default_value = "0.0.0.0:3030")]
     pub socket_address: String,
+
+    /// Path to a JSON file of `{username: argon2_hash}` entries. When set, requests must
+    /// carry a matching `Authorization` header; when unset, the server stays fully open.
+    #[clap(long)]
+    pub auth_token_file: Option<String>,
+
+    /// Directory for the append-only log and snapshot. When set, the database is restored
+    /// from it on startup and every mutation is persisted to disk; when unset, the
+    /// database is purely in-memory.
+    #[clap(long)]
+    pub data_dir: Option<String>,
 }
\ No newline at end of file
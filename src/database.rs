@@ -1,40 +1,182 @@
+use crate::batch::{BatchOp, BatchOperation, BatchResult};
+use crate::persistence::{FilePersistence, LogEntry, NoopPersistence, PersistedItem, Persistence};
+use crate::query::{QuerySpec, SortOrder};
+use serde::Serialize;
 use serde_json::{json, Value};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+/// Number of buffered events a `_changes` subscriber can fall behind before it starts
+/// missing events (and gets a `Lagged` error instead).
+const CHANGE_CHANNEL_CAPACITY: usize = 16;
+
+/// Number of mutations the append-only log accumulates before it is compacted into a
+/// fresh snapshot.
+const COMPACTION_THRESHOLD: usize = 1000;
 
 pub struct Database {
-    data: HashMap<String, HashMap<String, Arc<Value>>>,
+    data: HashMap<String, HashMap<String, StoredItem>>,
+    changes: HashMap<String, broadcast::Sender<ChangeEvent>>,
+    persistence: Box<dyn Persistence>,
+    mutations_since_compaction: usize,
+}
+
+/// A stored item together with its monotonically increasing version, used for
+/// optimistic-concurrency control via `ETag`/`If-Match`.
+#[derive(Debug, Clone)]
+pub struct StoredItem {
+    pub value: Arc<Value>,
+    pub version: u64,
+}
+
+/// The outcome of a `GET` lookup: either a single item (with its version) or, when the
+/// path does not resolve to an item, the whole collection.
+pub enum GetResult {
+    Item(StoredItem),
+    Collection(Value),
+}
+
+/// The outcome of a version-checked write (`update`/`merge`/`remove`).
+pub enum WriteResult {
+    Ok(StoredItem),
+    NotFound,
+    /// The `If-Match` version did not match the item's current version.
+    Conflict { current_version: u64 },
+    /// The patch body passed to `merge` was not a JSON object, so there was nothing to
+    /// shallow-merge into the existing item.
+    InvalidPatch,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Inserted,
+    Updated,
+    Merged,
+    Removed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub id: String,
+    pub value: Option<Arc<Value>>,
 }
 
 impl Database {
-    fn new() -> Self {
-        Self {
+    /// Rebuilds a database from `persistence`, replaying its snapshot and log (if any)
+    /// before serving any request.
+    fn restore(persistence: Box<dyn Persistence>) -> Result<Self, DatabaseError> {
+        let mut database = Self {
             data: HashMap::new(),
+            changes: HashMap::new(),
+            persistence,
+            mutations_since_compaction: 0,
+        };
+
+        for entry in database.persistence.load()? {
+            database.replay(entry);
         }
+
+        Ok(database)
     }
 
-    fn insert(&mut self, path: &str, json: Value) -> Arc<Value> {
+    /// Applies one previously persisted log entry to in-memory state, without touching
+    /// the log itself again.
+    fn replay(&mut self, entry: LogEntry) {
+        match entry {
+            LogEntry::Insert(item) | LogEntry::Update(item) | LogEntry::Merge(item) => {
+                let map = self.data.entry(item.path).or_default();
+                map.insert(
+                    item.id,
+                    StoredItem {
+                        value: Arc::new(item.value),
+                        version: item.version,
+                    },
+                );
+            }
+            LogEntry::Remove { path, id } => {
+                if let Some(map) = self.data.get_mut(&path) {
+                    map.remove(&id);
+                }
+            }
+        }
+    }
+
+    /// Appends `entry` to the persistence log and compacts once enough mutations have
+    /// accumulated. Errors are not propagated: a write that already succeeded in memory
+    /// should not fail because durability lagged behind.
+    fn record(&mut self, entry: LogEntry) {
+        let _ = self.persistence.append(&entry);
+        self.mutations_since_compaction += 1;
+        if self.mutations_since_compaction >= COMPACTION_THRESHOLD {
+            self.compact();
+        }
+    }
+
+    /// Writes the current state as a fresh snapshot and truncates the log, so restart
+    /// replay stays proportional to recent activity rather than the database's whole history.
+    fn compact(&mut self) {
+        let snapshot: Vec<PersistedItem> = self
+            .data
+            .iter()
+            .flat_map(|(path, map)| {
+                map.iter().map(move |(id, item)| PersistedItem {
+                    path: path.clone(),
+                    id: id.clone(),
+                    value: item.value.as_ref().to_owned(),
+                    version: item.version,
+                })
+            })
+            .collect();
+
+        let _ = self.persistence.compact(&snapshot);
+        self.mutations_since_compaction = 0;
+    }
+
+    fn insert(&mut self, path: &str, json: Value) -> StoredItem {
         let id = random_uuid();
         let value = add_id(&json, &id);
         let arc = Arc::new(value);
+        let item = StoredItem {
+            value: arc.clone(),
+            version: 1,
+        };
 
         if let Some(map) = self.data.get_mut(path) {
-            map.insert(id, arc.clone());
+            map.insert(id.clone(), item.clone());
         } else {
             let mut map = HashMap::new();
-            map.insert(id, arc.clone());
+            map.insert(id.clone(), item.clone());
             self.data.insert(path.to_string(), map);
         };
 
-        arc
+        self.record(LogEntry::Insert(PersistedItem {
+            path: path.to_string(),
+            id: id.clone(),
+            value: arc.as_ref().to_owned(),
+            version: item.version,
+        }));
+
+        self.publish(
+            path,
+            ChangeEvent {
+                kind: ChangeKind::Inserted,
+                id,
+                value: Some(arc),
+            },
+        );
+
+        item
     }
 
     fn get_all(&self, path: &str) -> Value {
         let values: Vec<Value> = if let Some(map) = self.data.get(path) {
             map.values()
-                .map(|v| v.to_owned())
-                .map(|x| x.as_ref().to_owned())
+                .map(|item| item.value.as_ref().to_owned())
                 .collect::<Vec<_>>()
         } else {
             vec![]
@@ -42,9 +184,267 @@ impl Database {
         json!({ "items": values })
     }
 
-    pub fn get_by_id(&self, path: &str, id: &str) -> Option<Value> {
+    pub fn get_by_id(&self, path: &str, id: &str) -> Option<StoredItem> {
         let map = self.data.get(path)?;
-        map.get(id).map(|a| a.to_owned().as_ref().to_owned())
+        map.get(id).cloned()
+    }
+
+    /// Replaces an existing item, keeping its id and bumping its version. Rejects with
+    /// [`WriteResult::Conflict`] if `expected_version` is set and does not match.
+    fn update(
+        &mut self,
+        path: &str,
+        id: &str,
+        json: Value,
+        expected_version: Option<u64>,
+    ) -> WriteResult {
+        let map = match self.data.get_mut(path) {
+            Some(map) => map,
+            None => return WriteResult::NotFound,
+        };
+        let current = match map.get(id) {
+            Some(current) => current,
+            None => return WriteResult::NotFound,
+        };
+        if let Some(expected) = expected_version {
+            if current.version != expected {
+                return WriteResult::Conflict {
+                    current_version: current.version,
+                };
+            }
+        }
+
+        let arc = Arc::new(add_id(&json, id));
+        let item = StoredItem {
+            value: arc.clone(),
+            version: current.version + 1,
+        };
+        map.insert(id.to_string(), item.clone());
+
+        self.record(LogEntry::Update(PersistedItem {
+            path: path.to_string(),
+            id: id.to_string(),
+            value: arc.as_ref().to_owned(),
+            version: item.version,
+        }));
+
+        self.publish(
+            path,
+            ChangeEvent {
+                kind: ChangeKind::Updated,
+                id: id.to_string(),
+                value: Some(arc),
+            },
+        );
+
+        WriteResult::Ok(item)
+    }
+
+    /// Shallow-merges `json` into the existing item and bumps its version. Rejects with
+    /// [`WriteResult::Conflict`] if `expected_version` is set and does not match.
+    fn merge(
+        &mut self,
+        path: &str,
+        id: &str,
+        json: Value,
+        expected_version: Option<u64>,
+    ) -> WriteResult {
+        if !json.is_object() {
+            return WriteResult::InvalidPatch;
+        }
+
+        let map = match self.data.get_mut(path) {
+            Some(map) => map,
+            None => return WriteResult::NotFound,
+        };
+        let current = match map.get(id) {
+            Some(current) => current,
+            None => return WriteResult::NotFound,
+        };
+        if let Some(expected) = expected_version {
+            if current.version != expected {
+                return WriteResult::Conflict {
+                    current_version: current.version,
+                };
+            }
+        }
+
+        let mut merged = current.value.as_ref().to_owned();
+        if let (Value::Object(base), Value::Object(patch)) = (&mut merged, &json) {
+            for (key, value) in patch {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+        let arc = Arc::new(merged);
+        let item = StoredItem {
+            value: arc.clone(),
+            version: current.version + 1,
+        };
+        map.insert(id.to_string(), item.clone());
+
+        self.record(LogEntry::Merge(PersistedItem {
+            path: path.to_string(),
+            id: id.to_string(),
+            value: arc.as_ref().to_owned(),
+            version: item.version,
+        }));
+
+        self.publish(
+            path,
+            ChangeEvent {
+                kind: ChangeKind::Merged,
+                id: id.to_string(),
+                value: Some(arc),
+            },
+        );
+
+        WriteResult::Ok(item)
+    }
+
+    /// Removes an item. Rejects with [`WriteResult::Conflict`] if `expected_version` is set
+    /// and does not match.
+    fn remove(&mut self, path: &str, id: &str, expected_version: Option<u64>) -> WriteResult {
+        let map = match self.data.get_mut(path) {
+            Some(map) => map,
+            None => return WriteResult::NotFound,
+        };
+        let current = match map.get(id) {
+            Some(current) => current,
+            None => return WriteResult::NotFound,
+        };
+        if let Some(expected) = expected_version {
+            if current.version != expected {
+                return WriteResult::Conflict {
+                    current_version: current.version,
+                };
+            }
+        }
+
+        let removed = map.remove(id).expect("checked above");
+
+        self.record(LogEntry::Remove {
+            path: path.to_string(),
+            id: id.to_string(),
+        });
+
+        self.publish(
+            path,
+            ChangeEvent {
+                kind: ChangeKind::Removed,
+                id: id.to_string(),
+                value: None,
+            },
+        );
+
+        WriteResult::Ok(removed)
+    }
+
+    /// Same dispatch as [`DatabaseAccess::get`], but runs under a single lock so it can be
+    /// reused from [`Database::apply_batch`].
+    fn get(&self, path: &str) -> Value {
+        let (parent, id) = split_path(path);
+        match self.get_by_id(&parent, id) {
+            Some(item) => item.value.as_ref().to_owned(),
+            None => self.get_all(path),
+        }
+    }
+
+    /// Applies `spec`'s filter, then sort, then pagination to the collection at `path`,
+    /// returning `{"items": [...], "total": T}` where `total` counts matches before
+    /// pagination.
+    pub fn query(&self, path: &str, spec: &QuerySpec) -> Value {
+        let mut items: Vec<Value> = self
+            .data
+            .get(path)
+            .map(|map| map.values().map(|item| item.value.as_ref().to_owned()).collect())
+            .unwrap_or_default();
+
+        for (field, expected) in &spec.filters {
+            items.retain(|item| {
+                item.get(field)
+                    .map(|value| field_matches(value, expected))
+                    .unwrap_or(false)
+            });
+        }
+
+        if let Some(sort_field) = &spec.sort {
+            items.sort_by(|a, b| compare_fields(a.get(sort_field), b.get(sort_field)));
+            if spec.order == SortOrder::Descending {
+                items.reverse();
+            }
+        }
+
+        let total = items.len();
+        let offset = spec.offset.min(items.len());
+        let items: Vec<Value> = match spec.limit {
+            Some(limit) => items.into_iter().skip(offset).take(limit).collect(),
+            None => items.into_iter().skip(offset).collect(),
+        };
+
+        json!({ "items": items, "total": total })
+    }
+
+    /// Publishes a change event to subscribers of `path`, if any. Silently drops the event
+    /// when nobody is listening.
+    fn publish(&self, path: &str, event: ChangeEvent) {
+        if let Some(sender) = self.changes.get(path) {
+            let _ = sender.send(event);
+        }
+    }
+
+    /// Subscribes to change events for `path`, creating the channel on first use.
+    fn subscribe(&mut self, path: &str) -> broadcast::Receiver<ChangeEvent> {
+        self.changes
+            .entry(path.to_string())
+            .or_insert_with(|| broadcast::channel(CHANGE_CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Applies a list of batch operations under a single lock, collecting one result per
+    /// operation so a failure in one does not abort the rest.
+    pub fn apply_batch(&mut self, operations: &[BatchOperation]) -> Vec<BatchResult> {
+        operations
+            .iter()
+            .map(|operation| self.apply_operation(operation))
+            .collect()
+    }
+
+    fn apply_operation(&mut self, operation: &BatchOperation) -> BatchResult {
+        match operation.op {
+            BatchOp::Insert => match operation.body.clone() {
+                Some(body) => {
+                    let item = self.insert(&operation.path, body);
+                    BatchResult::ok(201, item.value.as_ref().to_owned())
+                }
+                None => BatchResult::error(400, "Missing body for insert operation"),
+            },
+            BatchOp::Get => BatchResult::ok(200, self.get(&operation.path)),
+        }
+    }
+}
+
+/// True when a stored field's JSON value matches a raw query-string value.
+fn field_matches(value: &Value, expected: &str) -> bool {
+    match value {
+        Value::String(value) => value == expected,
+        other => {
+            let actual = other.to_string();
+            actual == expected
+        }
+    }
+}
+
+/// Orders two optional field values, treating a missing field as smaller than any present one.
+fn compare_fields(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    match (a, b) {
+        (Some(Value::Number(a)), Some(Value::Number(b))) => {
+            a.as_f64().partial_cmp(&b.as_f64()).unwrap_or(Ordering::Equal)
+        }
+        (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
+        (Some(a), Some(b)) => a.to_string().cmp(&b.to_string()),
+        (Some(_), None) => Ordering::Greater,
+        (None, Some(_)) => Ordering::Less,
+        (None, None) => Ordering::Equal,
     }
 }
 
@@ -60,53 +460,151 @@ fn add_id(json: &Value, uuid: &str) -> Value {
     value
 }
 
-pub type ConcurrentDatabase = Arc<Mutex<Database>>;
+/// Splits a request path into the parent (collection) path and the last segment (the id).
+fn split_path(path: &str) -> (String, &str) {
+    match path.split('/').collect::<Vec<_>>().as_slice() {
+        [parent @ .., id] => (parent.join("/"), id),
+        _ => (String::new(), path),
+    }
+}
+
+pub type ConcurrentDatabase = Arc<RwLock<Database>>;
+
+/// Opens a [`ConcurrentDatabase`], restoring it from `data_dir` if given. When `data_dir`
+/// is `None`, the database starts empty and stays purely in-memory.
+pub fn open(data_dir: Option<&str>) -> Result<ConcurrentDatabase, DatabaseError> {
+    let persistence: Box<dyn Persistence> = match data_dir {
+        Some(dir) => Box::new(FilePersistence::open(dir)?),
+        None => Box::new(NoopPersistence),
+    };
+    Ok(Arc::new(RwLock::new(Database::restore(persistence)?)))
+}
 
 pub trait DatabaseAccess {
-    fn new() -> Self;
-    fn insert(&mut self, path: &str, json: Value) -> Result<Arc<Value>, DatabaseError>;
-    /// Tries to get single item with id (in last component), otherwise gets all from path
-    fn get(&self, path: &str) -> Result<Value, DatabaseError>;
-    fn get_all(&self, path: &str) -> Result<Value, DatabaseError>;
-    fn get_by_id(&self, path: &str, id: &str) -> Result<Option<Value>, DatabaseError>;
+    fn insert(&mut self, path: &str, json: Value) -> Result<StoredItem, DatabaseError>;
+    /// Tries to get single item with id (in last component), otherwise gets all from path,
+    /// applying `query` (filter/sort/pagination) when it is not empty.
+    fn get(&self, path: &str, query: QuerySpec) -> Result<GetResult, DatabaseError>;
+    fn get_by_id(&self, path: &str, id: &str) -> Result<Option<StoredItem>, DatabaseError>;
+    /// Replaces the item at `path` (last component is the id), preserving its id. If
+    /// `expected_version` is set, the write is rejected with a conflict unless it matches
+    /// the item's current version.
+    fn update(
+        &mut self,
+        path: &str,
+        json: Value,
+        expected_version: Option<u64>,
+    ) -> Result<WriteResult, DatabaseError>;
+    /// Shallow-merges `json` into the item at `path` (last component is the id), honoring
+    /// `expected_version` the same way as [`DatabaseAccess::update`].
+    fn merge(
+        &mut self,
+        path: &str,
+        json: Value,
+        expected_version: Option<u64>,
+    ) -> Result<WriteResult, DatabaseError>;
+    /// Removes the item at `path` (last component is the id), honoring `expected_version`
+    /// the same way as [`DatabaseAccess::update`].
+    fn remove(
+        &mut self,
+        path: &str,
+        expected_version: Option<u64>,
+    ) -> Result<WriteResult, DatabaseError>;
+    /// Applies a batch of operations while holding the lock only once.
+    fn apply_batch(
+        &mut self,
+        operations: Vec<BatchOperation>,
+    ) -> Result<Vec<BatchResult>, DatabaseError>;
+    /// Subscribes to change events (inserts/updates/merges/removes) for items under `path`.
+    fn subscribe(&mut self, path: &str) -> Result<broadcast::Receiver<ChangeEvent>, DatabaseError>;
 }
 
 impl DatabaseAccess for ConcurrentDatabase {
-    fn new() -> ConcurrentDatabase {
-        Arc::new(Mutex::new(Database::new()))
-    }
-
-    fn insert(&mut self, path: &str, json: Value) -> Result<Arc<Value>, DatabaseError> {
+    fn insert(&mut self, path: &str, json: Value) -> Result<StoredItem, DatabaseError> {
         let mut database = self
-            .lock()
+            .write()
             .map_err(|_| DatabaseError::new("Cannot obtain lock"))?;
         Ok(database.insert(path, json))
     }
 
-    fn get(&self, path: &str) -> Result<Value, DatabaseError> {
-        let result = match path.split('/').collect::<Vec<_>>().as_slice() {
-            [parent @ .., id] => match self.get_by_id(&parent.join("/"), *id)? {
-                Some(value) => value,
-                None => self.get_all(path)?,
-            },
-            _ => self.get_all(path)?,
-        };
-        Ok(result)
-    }
+    fn get(&self, path: &str, query: QuerySpec) -> Result<GetResult, DatabaseError> {
+        let (parent, id) = split_path(path);
+        if let Some(item) = self.get_by_id(&parent, id)? {
+            return Ok(GetResult::Item(item));
+        }
 
-    fn get_all(&self, path: &str) -> Result<Value, DatabaseError> {
         let database = self
-            .lock()
+            .read()
             .map_err(|_| DatabaseError::new("Cannot obtain lock"))?;
-        Ok(database.get_all(path))
+        let value = if query.is_empty() {
+            database.get_all(path)
+        } else {
+            database.query(path, &query)
+        };
+        Ok(GetResult::Collection(value))
     }
 
-    fn get_by_id(&self, path: &str, id: &str) -> Result<Option<Value>, DatabaseError> {
+    fn get_by_id(&self, path: &str, id: &str) -> Result<Option<StoredItem>, DatabaseError> {
         let database = self
-            .lock()
+            .read()
             .map_err(|_| DatabaseError::new("Cannot obtain lock"))?;
         Ok(database.get_by_id(path, id))
     }
+
+    fn update(
+        &mut self,
+        path: &str,
+        json: Value,
+        expected_version: Option<u64>,
+    ) -> Result<WriteResult, DatabaseError> {
+        let (parent, id) = split_path(path);
+        let mut database = self
+            .write()
+            .map_err(|_| DatabaseError::new("Cannot obtain lock"))?;
+        Ok(database.update(&parent, id, json, expected_version))
+    }
+
+    fn merge(
+        &mut self,
+        path: &str,
+        json: Value,
+        expected_version: Option<u64>,
+    ) -> Result<WriteResult, DatabaseError> {
+        let (parent, id) = split_path(path);
+        let mut database = self
+            .write()
+            .map_err(|_| DatabaseError::new("Cannot obtain lock"))?;
+        Ok(database.merge(&parent, id, json, expected_version))
+    }
+
+    fn remove(
+        &mut self,
+        path: &str,
+        expected_version: Option<u64>,
+    ) -> Result<WriteResult, DatabaseError> {
+        let (parent, id) = split_path(path);
+        let mut database = self
+            .write()
+            .map_err(|_| DatabaseError::new("Cannot obtain lock"))?;
+        Ok(database.remove(&parent, id, expected_version))
+    }
+
+    fn apply_batch(
+        &mut self,
+        operations: Vec<BatchOperation>,
+    ) -> Result<Vec<BatchResult>, DatabaseError> {
+        let mut database = self
+            .write()
+            .map_err(|_| DatabaseError::new("Cannot obtain lock"))?;
+        Ok(database.apply_batch(&operations))
+    }
+
+    fn subscribe(&mut self, path: &str) -> Result<broadcast::Receiver<ChangeEvent>, DatabaseError> {
+        let mut database = self
+            .write()
+            .map_err(|_| DatabaseError::new("Cannot obtain lock"))?;
+        Ok(database.subscribe(path))
+    }
 }
 
 #[derive(Debug)]
@@ -115,7 +613,7 @@ pub struct DatabaseError {
 }
 
 impl DatabaseError {
-    fn new(message: &str) -> Self {
+    pub(crate) fn new(message: &str) -> Self {
         Self {
             message: message.to_string(),
         }
@@ -130,38 +628,47 @@ impl Display for DatabaseError {
 
 #[cfg(test)]
 mod tests {
-    use super::Database;
+    use super::{Database, WriteResult};
+    use crate::batch::{BatchOp, BatchOperation};
+    use crate::persistence::NoopPersistence;
+    use crate::query::QuerySpec;
     use serde_json::{json, Value};
+    use std::collections::HashMap;
+
+    fn new_database() -> Database {
+        Database::restore(Box::new(NoopPersistence)).unwrap()
+    }
 
     #[test]
     fn get_all_should_return_empty_json_if_no_data_inserted() {
-        let database = Database::new();
+        let database = new_database();
         let value = database.get_all("/api/v1/persons");
         assert_eq!(value, json!({"items": []}))
     }
 
     #[test]
-    fn should_insert_data_and_return_inserted() {
-        let mut database = Database::new();
+    fn should_insert_data_and_return_inserted_with_version_one() {
+        let mut database = new_database();
         let result = database.insert(
             "/api/v1/persons",
             json!({"firstName": "John", "lastName": "Doe"}),
         );
 
-        assert!(result.get("id").is_some());
+        assert_eq!(result.version, 1);
+        assert!(result.value.get("id").is_some());
         assert_eq!(
-            result.get("firstName"),
+            result.value.get("firstName"),
             Some(&Value::String("John".to_string()))
         );
         assert_eq!(
-            result.get("lastName"),
+            result.value.get("lastName"),
             Some(&Value::String("Doe".to_string()))
         );
     }
 
     #[test]
     fn get_all_should_return_inserted() {
-        let mut database = Database::new();
+        let mut database = new_database();
         database.insert(
             "/api/v1/persons",
             json!({"firstName": "John", "lastName": "Doe"}),
@@ -181,14 +688,325 @@ mod tests {
 
     #[test]
     fn get_by_id_should_return_inserted() {
-        let mut database = Database::new();
+        let mut database = new_database();
+        let inserted = database.insert(
+            "/api/v1/persons",
+            json!({"firstName": "John", "lastName": "Doe"}),
+        );
+        let id = inserted.value.get("id").unwrap().as_str().unwrap();
+        let item = database.get_by_id("/api/v1/persons", id);
+        assert!(item.is_some());
+        assert_eq!(inserted.value.as_ref(), item.unwrap().value.as_ref());
+    }
+
+    #[test]
+    fn update_should_replace_existing_item_preserve_id_and_bump_version() {
+        let mut database = new_database();
+        let inserted = database.insert(
+            "/api/v1/persons",
+            json!({"firstName": "John", "lastName": "Doe"}),
+        );
+        let id = inserted.value.get("id").unwrap().as_str().unwrap();
+
+        let updated = match database.update("/api/v1/persons", id, json!({"firstName": "Jane"}), None) {
+            WriteResult::Ok(item) => item,
+            _ => panic!("expected a successful write"),
+        };
+
+        assert_eq!(updated.version, 2);
+        assert_eq!(
+            updated.value.get("id"),
+            Some(&Value::String(id.to_string()))
+        );
+        assert_eq!(
+            updated.value.get("firstName"),
+            Some(&Value::String("Jane".to_string()))
+        );
+        assert_eq!(updated.value.get("lastName"), None);
+    }
+
+    #[test]
+    fn update_should_reject_mismatched_expected_version() {
+        let mut database = new_database();
+        let inserted = database.insert("/api/v1/persons", json!({"firstName": "John"}));
+        let id = inserted.value.get("id").unwrap().as_str().unwrap();
+
+        let result = database.update("/api/v1/persons", id, json!({}), Some(42));
+        assert!(matches!(
+            result,
+            WriteResult::Conflict {
+                current_version: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn update_should_return_not_found_if_item_does_not_exist() {
+        let mut database = new_database();
+        let result = database.update("/api/v1/persons", "missing", json!({}), None);
+        assert!(matches!(result, WriteResult::NotFound));
+    }
+
+    #[test]
+    fn merge_should_shallow_merge_into_existing_item_and_bump_version() {
+        let mut database = new_database();
+        let inserted = database.insert(
+            "/api/v1/persons",
+            json!({"firstName": "John", "lastName": "Doe"}),
+        );
+        let id = inserted.value.get("id").unwrap().as_str().unwrap();
+
+        let merged = match database.merge("/api/v1/persons", id, json!({"firstName": "Jane"}), None) {
+            WriteResult::Ok(item) => item,
+            _ => panic!("expected a successful write"),
+        };
+
+        assert_eq!(merged.version, 2);
+        assert_eq!(
+            merged.value.get("firstName"),
+            Some(&Value::String("Jane".to_string()))
+        );
+        assert_eq!(
+            merged.value.get("lastName"),
+            Some(&Value::String("Doe".to_string()))
+        );
+    }
+
+    #[test]
+    fn merge_should_reject_mismatched_expected_version() {
+        let mut database = new_database();
+        let inserted = database.insert("/api/v1/persons", json!({"firstName": "John"}));
+        let id = inserted.value.get("id").unwrap().as_str().unwrap();
+
+        let result = database.merge("/api/v1/persons", id, json!({}), Some(42));
+        assert!(matches!(
+            result,
+            WriteResult::Conflict {
+                current_version: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn merge_should_reject_non_object_patch_body_without_bumping_version() {
+        let mut database = new_database();
+        let inserted = database.insert("/api/v1/persons", json!({"firstName": "John"}));
+        let id = inserted.value.get("id").unwrap().as_str().unwrap();
+
+        let result = database.merge("/api/v1/persons", id, json!([9, 9]), None);
+        assert!(matches!(result, WriteResult::InvalidPatch));
+
+        let item = database.get_by_id("/api/v1/persons", id).unwrap();
+        assert_eq!(item.version, 1);
+    }
+
+    #[test]
+    fn merge_should_return_not_found_if_item_does_not_exist() {
+        let mut database = new_database();
+        let result = database.merge("/api/v1/persons", "missing", json!({}), None);
+        assert!(matches!(result, WriteResult::NotFound));
+    }
+
+    #[test]
+    fn remove_should_delete_existing_item() {
+        let mut database = new_database();
         let inserted = database.insert(
             "/api/v1/persons",
             json!({"firstName": "John", "lastName": "Doe"}),
         );
-        let id = inserted.get("id").unwrap().as_str().unwrap();
-        let value = database.get_by_id("/api/v1/persons", id);
-        assert!(value.is_some());
-        assert_eq!(inserted.as_ref(), &value.unwrap());
+        let id = inserted.value.get("id").unwrap().as_str().unwrap().to_string();
+
+        let removed = database.remove("/api/v1/persons", &id, None);
+        assert!(matches!(removed, WriteResult::Ok(_)));
+        assert!(database.get_by_id("/api/v1/persons", &id).is_none());
+    }
+
+    #[test]
+    fn remove_should_reject_mismatched_expected_version() {
+        let mut database = new_database();
+        let inserted = database.insert("/api/v1/persons", json!({"firstName": "John"}));
+        let id = inserted.value.get("id").unwrap().as_str().unwrap();
+
+        let result = database.remove("/api/v1/persons", id, Some(42));
+        assert!(matches!(
+            result,
+            WriteResult::Conflict {
+                current_version: 1
+            }
+        ));
+    }
+
+    #[test]
+    fn remove_should_return_not_found_if_item_does_not_exist() {
+        let mut database = new_database();
+        let result = database.remove("/api/v1/persons", "missing", None);
+        assert!(matches!(result, WriteResult::NotFound));
+    }
+
+    #[test]
+    fn subscribe_should_receive_events_for_inserts_and_removes() {
+        let mut database = new_database();
+        let mut receiver = database.subscribe("/api/v1/persons");
+
+        let inserted = database.insert(
+            "/api/v1/persons",
+            json!({"firstName": "John", "lastName": "Doe"}),
+        );
+        let id = inserted.value.get("id").unwrap().as_str().unwrap().to_string();
+        database.remove("/api/v1/persons", &id, None);
+
+        let inserted_event = receiver.try_recv().unwrap();
+        assert!(matches!(inserted_event.kind, super::ChangeKind::Inserted));
+        assert_eq!(inserted_event.id, id);
+
+        let removed_event = receiver.try_recv().unwrap();
+        assert!(matches!(removed_event.kind, super::ChangeKind::Removed));
+        assert_eq!(removed_event.id, id);
+    }
+
+    #[test]
+    fn apply_batch_should_insert_and_then_get_it_back() {
+        let mut database = new_database();
+        let results = database.apply_batch(&[
+            BatchOperation {
+                op: BatchOp::Insert,
+                path: "/api/v1/persons".to_string(),
+                body: Some(json!({"firstName": "John"})),
+            },
+            BatchOperation {
+                op: BatchOp::Get,
+                path: "/api/v1/persons".to_string(),
+                body: None,
+            },
+        ]);
+
+        assert_eq!(results[0].status, 201);
+        assert_eq!(results[1].status, 200);
+        let items = results[1].body.get("items").unwrap().as_array().unwrap();
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn apply_batch_should_report_individual_failure_without_aborting_batch() {
+        let mut database = new_database();
+        let results = database.apply_batch(&[
+            BatchOperation {
+                op: BatchOp::Insert,
+                path: "/api/v1/persons".to_string(),
+                body: None,
+            },
+            BatchOperation {
+                op: BatchOp::Get,
+                path: "/api/v1/persons".to_string(),
+                body: None,
+            },
+        ]);
+
+        assert_eq!(results[0].status, 400);
+        assert_eq!(results[1].status, 200);
+    }
+
+    #[test]
+    fn query_should_filter_by_top_level_field() {
+        let mut database = new_database();
+        database.insert("/api/v1/persons", json!({"firstName": "John"}));
+        database.insert("/api/v1/persons", json!({"firstName": "Jane"}));
+
+        let mut params = HashMap::new();
+        params.insert("firstName".to_string(), "Jane".to_string());
+        let spec = QuerySpec::from_params(&params);
+
+        let result = database.query("/api/v1/persons", &spec);
+        let items = result.get("items").unwrap().as_array().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].get("firstName"),
+            Some(&Value::String("Jane".to_string()))
+        );
+        assert_eq!(result.get("total"), Some(&json!(1)));
+    }
+
+    #[test]
+    fn query_should_sort_and_paginate() {
+        let mut database = new_database();
+        database.insert("/api/v1/persons", json!({"age": 30}));
+        database.insert("/api/v1/persons", json!({"age": 10}));
+        database.insert("/api/v1/persons", json!({"age": 20}));
+
+        let mut params = HashMap::new();
+        params.insert("sort".to_string(), "age".to_string());
+        params.insert("order".to_string(), "asc".to_string());
+        params.insert("limit".to_string(), "1".to_string());
+        params.insert("offset".to_string(), "1".to_string());
+        let spec = QuerySpec::from_params(&params);
+
+        let result = database.query("/api/v1/persons", &spec);
+        let items = result.get("items").unwrap().as_array().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].get("age"), Some(&json!(20)));
+        assert_eq!(result.get("total"), Some(&json!(3)));
+    }
+
+    #[test]
+    fn concurrent_reads_should_not_block_each_other() {
+        use super::ConcurrentDatabase;
+        use std::sync::Barrier;
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        let database: ConcurrentDatabase = super::open(None).unwrap();
+        database
+            .write()
+            .unwrap()
+            .insert("/api/v1/persons", json!({"firstName": "John"}));
+
+        const READERS: usize = 8;
+        const HOLD_TIME: Duration = Duration::from_millis(50);
+        let barrier = std::sync::Arc::new(Barrier::new(READERS));
+        let start = Instant::now();
+
+        let handles: Vec<_> = (0..READERS)
+            .map(|_| {
+                let database = database.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let _guard = database.read().unwrap();
+                    thread::sleep(HOLD_TIME);
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        // If reads serialized behind a single lock this would take roughly
+        // READERS * HOLD_TIME; concurrent reads should finish much sooner.
+        assert!(start.elapsed() < HOLD_TIME * (READERS as u32) / 2);
+    }
+
+    #[test]
+    fn restore_should_replay_log_and_snapshot_written_by_a_previous_instance() {
+        use crate::persistence::FilePersistence;
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("in-mem-rest-api-test-{}", super::random_uuid()));
+
+        {
+            let persistence = FilePersistence::open(dir.to_str().unwrap()).unwrap();
+            let mut database = Database::restore(Box::new(persistence)).unwrap();
+            database.insert("/api/v1/persons", json!({"firstName": "John"}));
+            database.compact();
+            database.insert("/api/v1/persons", json!({"firstName": "Jane"}));
+        }
+
+        let persistence = FilePersistence::open(dir.to_str().unwrap()).unwrap();
+        let restored = Database::restore(Box::new(persistence)).unwrap();
+        let items = restored.get_all("/api/v1/persons");
+        let items = items.get("items").unwrap().as_array().unwrap();
+        assert_eq!(items.len(), 2);
+
+        fs::remove_dir_all(&dir).ok();
     }
 }
@@ -1,55 +1,305 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::error::Error;
 use std::net::SocketAddr;
+use std::sync::Arc;
+use futures_util::StreamExt;
 use serde_json::{json, Value};
+use tokio_stream::wrappers::BroadcastStream;
 use warp::http::Response;
 use warp::path::FullPath;
+use warp::sse::Event;
 use warp::Filter;
 use clap::Parser;
 use crate::cli_parameters::CliParams;
 
-use crate::database::{ConcurrentDatabase, DatabaseAccess, DatabaseError};
+use crate::auth::{AuthGate, AuthOutcome};
+use crate::batch::BatchOperation;
+use crate::database::{ConcurrentDatabase, DatabaseAccess, DatabaseError, GetResult, WriteResult};
+use crate::query::QuerySpec;
 
+mod auth;
+mod batch;
 mod database;
+mod persistence;
+mod query;
 mod cli_parameters;
 
+#[derive(Debug)]
+struct AuthRejection(u16);
+
+impl warp::reject::Reject for AuthRejection {}
+
+/// Builds the auth gate filter. When `gate` is `None` (no `--auth-token-file`), every
+/// request passes through unchanged.
+fn auth_filter(
+    gate: Option<Arc<AuthGate>>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let gate = gate.clone();
+            async move {
+                match &gate {
+                    None => Ok(()),
+                    Some(gate) => match gate.authorize(header) {
+                        AuthOutcome::Authorized => Ok(()),
+                        AuthOutcome::Missing => Err(warp::reject::custom(AuthRejection(403))),
+                        AuthOutcome::Unauthorized => Err(warp::reject::custom(AuthRejection(401))),
+                    },
+                }
+            }
+        })
+        .untuple_one()
+}
+
+async fn handle_rejection(rejection: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
+    if let Some(AuthRejection(status)) = rejection.find::<AuthRejection>() {
+        let message = if *status == 401 {
+            "Unauthorized"
+        } else {
+            "Missing credentials"
+        };
+        return Ok(Response::builder()
+            .status(*status)
+            .body(json!({ "error": message }).to_string()));
+    }
+
+    if rejection
+        .find::<warp::filters::body::BodyDeserializeError>()
+        .is_some()
+    {
+        return Ok(Response::builder()
+            .status(400)
+            .body(json!({ "error": "Invalid request body" }).to_string()));
+    }
+
+    if rejection.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        return Ok(Response::builder()
+            .status(415)
+            .body(json!({ "error": "Unsupported media type" }).to_string()));
+    }
+
+    if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        return Ok(Response::builder()
+            .status(405)
+            .body(json!({ "error": "Method not allowed" }).to_string()));
+    }
+
+    Ok(Response::builder()
+        .status(404)
+        .body(json!({ "error": "Not found" }).to_string()))
+}
+
+/// Formats a version as a strong `ETag` value.
+fn etag(version: u64) -> String {
+    format!("\"{}\"", version)
+}
+
+/// Parses an `If-Match` header value (with or without surrounding quotes) into a version.
+fn parse_if_match(header: Option<String>) -> Option<u64> {
+    header
+        .as_deref()
+        .map(|value| value.trim_matches('"'))
+        .and_then(|value| value.parse::<u64>().ok())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let params: CliParams = CliParams::parse();
     let socket_address: SocketAddr = params.socket_address.parse::<SocketAddr>()
         .map_err(|e| format!("Cannot parse socket address: {}", e))?;
 
-    let original: ConcurrentDatabase = DatabaseAccess::new();
+    let gate = match &params.auth_token_file {
+        Some(path) => Some(Arc::new(AuthGate::load(path)?)),
+        None => None,
+    };
+
+    let original: ConcurrentDatabase = database::open(params.data_dir.as_deref())
+        .map_err(|e| format!("Cannot open database: {}", e))?;
 
     let database: ConcurrentDatabase = original.clone();
     let get = warp::get()
+        .and(auth_filter(gate.clone()))
         .and(warp::path::full())
-        .map(move |path: FullPath| match database.get(path.as_str()) {
-            Ok(value) => Response::builder()
-                .status(200)
-                .header("Content-Type", "application/json")
-                .body(value.to_string()),
-            Err(DatabaseError { message }) => Response::builder()
-                .status(500)
-                .body(json!({ "error": message }).to_string()),
+        .and(warp::query::<HashMap<String, String>>())
+        .map(move |path: FullPath, params: HashMap<String, String>| {
+            match database.get(path.as_str(), QuerySpec::from_params(&params)) {
+                Ok(GetResult::Item(item)) => Response::builder()
+                    .status(200)
+                    .header("Content-Type", "application/json")
+                    .header("ETag", etag(item.version))
+                    .body(item.value.to_string()),
+                Ok(GetResult::Collection(value)) => Response::builder()
+                    .status(200)
+                    .header("Content-Type", "application/json")
+                    .body(value.to_string()),
+                Err(DatabaseError { message }) => Response::builder()
+                    .status(500)
+                    .body(json!({ "error": message }).to_string()),
+            }
         });
 
     let database = original.clone();
     let post = warp::post()
+        .and(auth_filter(gate.clone()))
         .and(warp::path::full())
         .and(warp::body::json())
         .map(move |path: FullPath, json: Value| {
             match database.clone().insert(path.as_str(), json) {
-                Ok(value) => Response::builder()
+                Ok(item) => Response::builder()
                     .status(201)
                     .header("Content-Type", "application/json")
-                    .body((*value).to_string()),
+                    .header("ETag", etag(item.version))
+                    .body(item.value.to_string()),
+                Err(DatabaseError { message }) => Response::builder()
+                    .status(500)
+                    .body(json!({ "error": message }).to_string()),
+            }
+        });
+
+    let database = original.clone();
+    let put = warp::put()
+        .and(auth_filter(gate.clone()))
+        .and(warp::path::full())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(warp::body::json())
+        .map(move |path: FullPath, if_match: Option<String>, json: Value| {
+            let expected_version = parse_if_match(if_match);
+            match database.clone().update(path.as_str(), json, expected_version) {
+                Ok(WriteResult::Ok(item)) => Response::builder()
+                    .status(200)
+                    .header("Content-Type", "application/json")
+                    .header("ETag", etag(item.version))
+                    .body(item.value.to_string()),
+                Ok(WriteResult::NotFound) => Response::builder()
+                    .status(404)
+                    .body(json!({ "error": "Not found" }).to_string()),
+                Ok(WriteResult::Conflict { current_version }) => Response::builder()
+                    .status(412)
+                    .header("ETag", etag(current_version))
+                    .body(json!({ "error": "Precondition failed" }).to_string()),
+                Ok(WriteResult::InvalidPatch) => Response::builder()
+                    .status(500)
+                    .body(json!({ "error": "Unexpected write result" }).to_string()),
+                Err(DatabaseError { message }) => Response::builder()
+                    .status(500)
+                    .body(json!({ "error": message }).to_string()),
+            }
+        });
+
+    let database = original.clone();
+    let patch = warp::patch()
+        .and(auth_filter(gate.clone()))
+        .and(warp::path::full())
+        .and(warp::header::optional::<String>("if-match"))
+        .and(warp::body::json())
+        .map(move |path: FullPath, if_match: Option<String>, json: Value| {
+            let expected_version = parse_if_match(if_match);
+            match database.clone().merge(path.as_str(), json, expected_version) {
+                Ok(WriteResult::Ok(item)) => Response::builder()
+                    .status(200)
+                    .header("Content-Type", "application/json")
+                    .header("ETag", etag(item.version))
+                    .body(item.value.to_string()),
+                Ok(WriteResult::NotFound) => Response::builder()
+                    .status(404)
+                    .body(json!({ "error": "Not found" }).to_string()),
+                Ok(WriteResult::Conflict { current_version }) => Response::builder()
+                    .status(412)
+                    .header("ETag", etag(current_version))
+                    .body(json!({ "error": "Precondition failed" }).to_string()),
+                Ok(WriteResult::InvalidPatch) => Response::builder()
+                    .status(400)
+                    .body(json!({ "error": "Patch body must be a JSON object" }).to_string()),
+                Err(DatabaseError { message }) => Response::builder()
+                    .status(500)
+                    .body(json!({ "error": message }).to_string()),
+            }
+        });
+
+    let database = original.clone();
+    let delete = warp::delete()
+        .and(auth_filter(gate.clone()))
+        .and(warp::path::full())
+        .and(warp::header::optional::<String>("if-match"))
+        .map(move |path: FullPath, if_match: Option<String>| {
+            let expected_version = parse_if_match(if_match);
+            match database.clone().remove(path.as_str(), expected_version) {
+                Ok(WriteResult::Ok(_)) => Response::builder().status(204).body(String::new()),
+                Ok(WriteResult::NotFound) => Response::builder()
+                    .status(404)
+                    .body(json!({ "error": "Not found" }).to_string()),
+                Ok(WriteResult::Conflict { current_version }) => Response::builder()
+                    .status(412)
+                    .header("ETag", etag(current_version))
+                    .body(json!({ "error": "Precondition failed" }).to_string()),
+                Ok(WriteResult::InvalidPatch) => Response::builder()
+                    .status(500)
+                    .body(json!({ "error": "Unexpected write result" }).to_string()),
+                Err(DatabaseError { message }) => Response::builder()
+                    .status(500)
+                    .body(json!({ "error": message }).to_string()),
+            }
+        });
+
+    let database = original.clone();
+    let batch = warp::post()
+        .and(warp::path("_batch"))
+        .and(warp::path::end())
+        .and(auth_filter(gate.clone()))
+        .and(warp::body::json())
+        .map(move |operations: Vec<BatchOperation>| {
+            match database.clone().apply_batch(operations) {
+                Ok(results) => Response::builder()
+                    .status(200)
+                    .header("Content-Type", "application/json")
+                    .body(json!(results).to_string()),
                 Err(DatabaseError { message }) => Response::builder()
                     .status(500)
                     .body(json!({ "error": message }).to_string()),
             }
         });
 
-    warp::serve(post.or(get)).run(socket_address).await;
+    let database = original.clone();
+    let changes = warp::get()
+        .and(warp::path::full())
+        .and_then(|path: FullPath| async move {
+            path.as_str()
+                .strip_suffix("/_changes")
+                .map(|collection| collection.to_string())
+                .ok_or_else(warp::reject::not_found)
+        })
+        .and(auth_filter(gate.clone()))
+        .and_then(move |collection: String| {
+            let mut database = database.clone();
+            async move {
+                let receiver = database
+                    .subscribe(&collection)
+                    .map_err(|_| warp::reject::not_found())?;
+
+                let stream = BroadcastStream::new(receiver).filter_map(|event| async move {
+                    match event {
+                        Ok(event) => Event::default().json_data(&event).ok().map(Ok::<_, Infallible>),
+                        Err(_lagged) => None,
+                    }
+                });
+
+                Ok::<_, warp::Rejection>(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+            }
+        });
+
+    warp::serve(
+        batch
+            .or(changes)
+            .or(post)
+            .or(get)
+            .or(put)
+            .or(patch)
+            .or(delete)
+            .recover(handle_rejection),
+    )
+    .run(socket_address)
+    .await;
 
     Ok(())
 }
@@ -0,0 +1,134 @@
+use crate::database::DatabaseError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A single stored item, as written to the snapshot and the append-only log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedItem {
+    pub path: String,
+    pub id: String,
+    pub value: Value,
+    pub version: u64,
+}
+
+/// One append-only log entry. Replaying a snapshot followed by the log's entries, in
+/// order, reconstructs the in-memory state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum LogEntry {
+    Insert(PersistedItem),
+    Update(PersistedItem),
+    Merge(PersistedItem),
+    Remove { path: String, id: String },
+}
+
+/// Durability backend for [`crate::database::Database`]. The hot path only ever appends
+/// and periodically compacts; durability itself is fully opt-in via [`NoopPersistence`].
+pub trait Persistence: Send + Sync {
+    fn append(&self, entry: &LogEntry) -> Result<(), DatabaseError>;
+    fn load(&self) -> Result<Vec<LogEntry>, DatabaseError>;
+    fn compact(&self, snapshot: &[PersistedItem]) -> Result<(), DatabaseError>;
+}
+
+/// No-op default used when `--data-dir` is not set: nothing is written, nothing is read
+/// back, and the database stays purely in-memory as before.
+pub struct NoopPersistence;
+
+impl Persistence for NoopPersistence {
+    fn append(&self, _entry: &LogEntry) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<LogEntry>, DatabaseError> {
+        Ok(Vec::new())
+    }
+
+    fn compact(&self, _snapshot: &[PersistedItem]) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+}
+
+/// Writes each mutation as a JSON line to `<data-dir>/log.jsonl` and compacts into
+/// `<data-dir>/snapshot.json`.
+pub struct FilePersistence {
+    dir: PathBuf,
+    log: Mutex<File>,
+}
+
+impl FilePersistence {
+    pub fn open(dir: &str) -> Result<Self, DatabaseError> {
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).map_err(to_database_error)?;
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path(&dir))
+            .map_err(to_database_error)?;
+        Ok(Self {
+            dir,
+            log: Mutex::new(log),
+        })
+    }
+}
+
+impl Persistence for FilePersistence {
+    fn append(&self, entry: &LogEntry) -> Result<(), DatabaseError> {
+        let line = serde_json::to_string(entry).map_err(to_database_error)?;
+        let mut log = self
+            .log
+            .lock()
+            .map_err(|_| DatabaseError::new("Cannot obtain log lock"))?;
+        writeln!(log, "{}", line).map_err(to_database_error)
+    }
+
+    fn load(&self) -> Result<Vec<LogEntry>, DatabaseError> {
+        let mut entries = Vec::new();
+
+        if let Ok(contents) = fs::read_to_string(snapshot_path(&self.dir)) {
+            let snapshot: Vec<PersistedItem> =
+                serde_json::from_str(&contents).map_err(to_database_error)?;
+            entries.extend(snapshot.into_iter().map(LogEntry::Insert));
+        }
+
+        if let Ok(contents) = fs::read_to_string(log_path(&self.dir)) {
+            for line in contents.lines().filter(|line| !line.is_empty()) {
+                entries.push(serde_json::from_str(line).map_err(to_database_error)?);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn compact(&self, snapshot: &[PersistedItem]) -> Result<(), DatabaseError> {
+        let contents = serde_json::to_string(snapshot).map_err(to_database_error)?;
+        fs::write(snapshot_path(&self.dir), contents).map_err(to_database_error)?;
+
+        let mut log = self
+            .log
+            .lock()
+            .map_err(|_| DatabaseError::new("Cannot obtain log lock"))?;
+        *log = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(log_path(&self.dir))
+            .map_err(to_database_error)?;
+        Ok(())
+    }
+}
+
+fn snapshot_path(dir: &Path) -> PathBuf {
+    dir.join("snapshot.json")
+}
+
+fn log_path(dir: &Path) -> PathBuf {
+    dir.join("log.jsonl")
+}
+
+fn to_database_error(error: impl std::fmt::Display) -> DatabaseError {
+    DatabaseError::new(&error.to_string())
+}
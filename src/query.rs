@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+
+/// Parsed `?field=value&sort=...&order=...&limit=...&offset=...` query parameters for a
+/// collection `GET`. Any parameter that isn't one of the reserved names (`sort`, `order`,
+/// `limit`, `offset`) is treated as an equality filter against a top-level field.
+#[derive(Debug, Default)]
+pub struct QuerySpec {
+    pub filters: Vec<(String, String)>,
+    pub sort: Option<String>,
+    pub order: SortOrder,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+impl QuerySpec {
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        let mut spec = QuerySpec::default();
+        for (key, value) in params {
+            match key.as_str() {
+                "sort" => spec.sort = Some(value.clone()),
+                "order" => {
+                    spec.order = if value.eq_ignore_ascii_case("desc") {
+                        SortOrder::Descending
+                    } else {
+                        SortOrder::Ascending
+                    }
+                }
+                "limit" => spec.limit = value.parse().ok(),
+                "offset" => spec.offset = value.parse().unwrap_or(0),
+                _ => spec.filters.push((key.clone(), value.clone())),
+            }
+        }
+        spec
+    }
+
+    /// True when no query parameters were given, so callers can fall back to the old,
+    /// unfiltered response shape.
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty() && self.sort.is_none() && self.limit.is_none() && self.offset == 0
+    }
+}